@@ -20,6 +20,7 @@ Usage: portable_env [options]
 Options:
     --config=FILE  Location of the config file. [default: ./portable_env.toml]
     --output=DIR  Where to put output script directories. [default: .]
+    --check  Validate the config and list the scripts it would write, without writing or removing any files.
 ";
 
 static AUTOREMOVE_MARKER: &'static str = "this-file-is-marked-for-removal-on-generation";
@@ -31,23 +32,98 @@ enum ModType {
     PREPEND_PATH,
     APPEND_PATH,
     SET,
-    PATH
+    PATH,
+    REMOVE_PATH
 }
 
 #[derive(Clone, Copy)]
 enum EnvType {
     CMD,
     BASH,
-    POWERSHELL
+    POWERSHELL,
+    FISH
 }
 
-fn generate_fix_path(path: &str, t: EnvType) -> String {
+#[derive(Clone, Copy)]
+#[allow(non_camel_case_types)]
+enum PathStyle {
+    CYGPATH,
+    WSLPATH,
+    MSYS,
+    NONE
+}
+
+fn get_path_style_by_str(s: &str) -> Result<PathStyle, String> {
+    match s {
+        "cygpath" => Ok(PathStyle::CYGPATH),
+        "wslpath" => Ok(PathStyle::WSLPATH),
+        "msys" => Ok(PathStyle::MSYS),
+        "none" => Ok(PathStyle::NONE),
+        _ => Err(format!("invalid path_style:{}", s))
+    }
+}
+
+// Converts a Windows-style `C:\Users\x` path to the MSYS2/Git-Bash POSIX form
+// `/c/Users/x`. Already-POSIX and relative paths are left untouched.
+fn convert_path_msys(path: &str) -> String {
+    let chars: Vec<char> = path.chars().collect();
+    if chars.len() >= 2 && chars[0].is_ascii_alphabetic() && chars[1] == ':' {
+        let drive = chars[0].to_ascii_lowercase();
+        let rest: String = chars[2..].iter().collect::<String>().replace('\\', "/");
+        format!("/{}{}", drive, rest)
+    } else {
+        path.to_string()
+    }
+}
+
+#[test]
+fn test_convert_path_msys() {
+    assert_eq!(convert_path_msys("C:\\Users\\x"), "/c/Users/x");
+    assert_eq!(convert_path_msys("D:\\tools\\bin"), "/d/tools/bin");
+}
+
+#[test]
+fn test_convert_path_msys_leaves_posix_and_relative_paths_untouched() {
+    assert_eq!(convert_path_msys("/usr/local/bin"), "/usr/local/bin");
+    assert_eq!(convert_path_msys("relative/path"), "relative/path");
+}
+
+fn generate_fix_path(path: &str, t: EnvType, translate_path: bool, style: PathStyle) -> String {
     match t {
-        EnvType::CMD | EnvType::POWERSHELL => format!("{}", path),
-        EnvType::BASH => format!("`cygpath -p  \"{}\"`", escape_bash_vars(path))
+        EnvType::BASH if translate_path => match style {
+            PathStyle::CYGPATH => format!("`cygpath -p  \"{}\"`", escape_bash_vars(path)),
+            PathStyle::WSLPATH => format!("$(wslpath \"{}\")", escape_bash_vars(path)),
+            PathStyle::MSYS => convert_path_msys(path),
+            PathStyle::NONE => format!("{}", path),
+        },
+        _ => format!("{}", path)
     }
 }
 
+// PATH-family modifications are the only ones that point at real filesystem
+// paths by default; everything else (e.g. CLASSPATH-style scalars set via SET)
+// is left alone unless the config opts in with `translate_path`.
+fn default_translate_path(m: ModType, e: EnvType) -> bool {
+    match (m, e) {
+        (ModType::PREPEND_PATH, EnvType::BASH) |
+        (ModType::APPEND_PATH, EnvType::BASH) |
+        (ModType::PATH, EnvType::BASH) |
+        (ModType::REMOVE_PATH, EnvType::BASH) => true,
+        _ => false
+    }
+}
+
+#[test]
+fn test_default_translate_path_remove_matches_prepend() {
+    // REMOVE_PATH's whole point is undoing a prior PREPEND_PATH/APPEND_PATH,
+    // so it must default to the same translate_path as those on bash --
+    // otherwise it compares the raw entry against the translated value that's
+    // actually stored in the variable and never matches.
+    assert_eq!(default_translate_path(ModType::REMOVE_PATH, EnvType::BASH),
+               default_translate_path(ModType::PREPEND_PATH, EnvType::BASH));
+    assert!(default_translate_path(ModType::REMOVE_PATH, EnvType::BASH));
+}
+
 fn escape_bash_vars(s: &str) -> String {
     s.replace("$", "\\$")
 }
@@ -56,7 +132,8 @@ fn generate_get_env(name: &str, e: EnvType) -> String {
     match e {
         EnvType::CMD => format!("%{}%", name),
         EnvType::BASH  => format!("${{{}}}", name),
-        EnvType::POWERSHELL => format!("${{env:{}}}", name)
+        EnvType::POWERSHELL => format!("${{env:{}}}", name),
+        EnvType::FISH => format!("${}", name)
     }
 }
 
@@ -64,16 +141,18 @@ fn generate_separator(e: EnvType) -> &'static str {
     match e {
         EnvType::CMD => ";",
         EnvType::BASH  => ":",
-        EnvType::POWERSHELL => ";"
+        EnvType::POWERSHELL => ";",
+        // fish treats list vars as real lists, joined by spaces, not a separator string
+        EnvType::FISH => " "
     }
 }
 
-fn transform_vars(value: &str, e: EnvType) -> String {
+fn transform_vars(value: &str, e: EnvType) -> Result<String, String> {
     match e {
-        EnvType::CMD => value.to_string(),
-        EnvType::BASH | EnvType::POWERSHELL => {
+        EnvType::CMD => Ok(value.to_string()),
+        EnvType::BASH | EnvType::POWERSHELL | EnvType::FISH => {
             if value.len() == 0 {
-                String::new()
+                Ok(String::new())
             }
             else {
                 let words: &[&str] =  &value.split('%').collect::<Vec<&str>>();
@@ -88,8 +167,9 @@ fn transform_vars(value: &str, e: EnvType) -> String {
                         else {
                             let new_var = match e {
                                 EnvType::BASH => format!("${{{}}}", word),
-                                EnvType::CMD => panic!(),
-                                EnvType::POWERSHELL => format!("${{env:{}}}", word)
+                                EnvType::CMD => unreachable!(),
+                                EnvType::POWERSHELL => format!("${{env:{}}}", word),
+                                EnvType::FISH => format!("${}", word)
                             };
                             ret.push_str(&new_var);
                         }
@@ -99,9 +179,9 @@ fn transform_vars(value: &str, e: EnvType) -> String {
                     }
                 }
                 if var && value.chars().last().unwrap() != '%' {
-                    panic!("incorrect % string in:{}", value)
+                    return Err(format!("incorrect % string in:{}", value))
                 }
-                ret
+                Ok(ret)
             }
         }
     }
@@ -109,60 +189,287 @@ fn transform_vars(value: &str, e: EnvType) -> String {
 
 #[test]
 fn test_transform_vars() {
-    assert_eq!(transform_vars("", EnvType::BASH), "");
-    assert_eq!(transform_vars("b", EnvType::BASH), "b");
-    assert_eq!(transform_vars("%ASD%", EnvType::BASH), "${ASD}");
-    assert_eq!(transform_vars("%ASD%b", EnvType::BASH), "${ASD}b");
-    assert_eq!(transform_vars("a%ASD%b", EnvType::BASH), "a${ASD}b");
-    assert_eq!(transform_vars("a%%ASDb", EnvType::BASH), "a%ASDb");
+    assert_eq!(transform_vars("", EnvType::BASH).unwrap(), "");
+    assert_eq!(transform_vars("b", EnvType::BASH).unwrap(), "b");
+    assert_eq!(transform_vars("%ASD%", EnvType::BASH).unwrap(), "${ASD}");
+    assert_eq!(transform_vars("%ASD%b", EnvType::BASH).unwrap(), "${ASD}b");
+    assert_eq!(transform_vars("a%ASD%b", EnvType::BASH).unwrap(), "a${ASD}b");
+    assert_eq!(transform_vars("a%%ASDb", EnvType::BASH).unwrap(), "a%ASDb");
 }
 
 #[test]
-#[should_panic]
 fn test_transform_vars_fail() {
-    transform_vars("a%b", EnvType::BASH);
+    assert!(transform_vars("a%b", EnvType::BASH).is_err());
 }
 
 #[test]
-#[should_panic]
 fn test_transform_vars_fail2() {
-    transform_vars("a%%ASD%b", EnvType::BASH);
+    assert!(transform_vars("a%%ASD%b", EnvType::BASH).is_err());
+}
+
+#[test]
+fn test_transform_vars_fish() {
+    assert_eq!(transform_vars("", EnvType::FISH).unwrap(), "");
+    assert_eq!(transform_vars("%ASD%b", EnvType::FISH).unwrap(), "$ASDb");
+    assert_eq!(transform_vars("a%%ASDb", EnvType::FISH).unwrap(), "a%ASDb");
 }
 
 fn generate_mod_env_set_value(eval_value: &str, t: EnvType) -> String {
     match t {
-        EnvType::CMD | EnvType::POWERSHELL => format!("{}", eval_value),
+        EnvType::CMD | EnvType::POWERSHELL | EnvType::FISH => format!("{}", eval_value),
         EnvType::BASH => format!("'{}'", &eval_value),
     }
 }
 
-fn generate_mod_env_value(name: &str, value: &str, m: ModType, e: EnvType) -> String {
-    let eval_value = transform_vars(value, e);
-    match m {
+// Wraps a newly-built PREPEND_PATH/APPEND_PATH value expression so duplicate
+// entries are collapsed, keeping the first occurrence. Only meaningful on
+// bash/powershell/fish, which can express this as a pipeline over the
+// separator-delimited (or, for fish, space-delimited) list; cmd's dedup is
+// implemented as its own for-loop block in `generate_remove_or_dedup_cmd`.
+fn wrap_dedup(e: EnvType, sep: &str, expr: &str) -> String {
+    match e {
+        EnvType::BASH => format!("$(printf '%s' \"{}\" | awk -v RS='{}' -v ORS='{}' '!seen[$0]++' | sed 's/{}$//')", expr, sep, sep, sep),
+        EnvType::POWERSHELL => format!("((\"{}\") -split '{}' | Select-Object -Unique) -join '{}'", expr, sep, sep),
+        EnvType::FISH => format!("(for _peis_x in {}; echo $_peis_x; end | awk '!seen[$0]++')", expr),
+        EnvType::CMD => expr.to_string(),
+    }
+}
+
+// REMOVE_PATH's value expression: strips the exact entry `entry` out of the
+// current value of `name`, preserving the order of what's left.
+fn generate_remove_path_value(name: &str, entry: &str, e: EnvType, sep: &str) -> String {
+    match e {
+        EnvType::BASH => format!("$(printf '%s' \"{}\" | awk -v RS='{}' -v ORS='{}' '$0!=\"{}\"' | sed 's/{}$//')", generate_get_env(name, e), sep, sep, entry, sep),
+        EnvType::POWERSHELL => format!("(({} -split '{}') | Where-Object {{ $_ -ne '{}' }}) -join '{}'", generate_get_env(name, e), sep, entry, sep),
+        EnvType::FISH => format!("(string match -v -- '{}' ${})", entry, name),
+        EnvType::CMD => unreachable!("REMOVE_PATH on cmd is emitted as its own for-loop block"),
+    }
+}
+
+// cmd can't filter a list inline as part of a `set NAME=value` expression, so
+// REMOVE_PATH and dedup are emitted as their own for-loop block over
+// `%NAME:sep=" "%`-tokenized entries instead of going through
+// generate_mod_env_value/generate_mod_env's generic templates. The entries
+// iterated are those of `candidate_value`, copied into `_PEIS_TMP` and
+// tokenized as `%_PEIS_TMP:sep=" "%`. Both variants enable delayed expansion
+// so the loop body sees `_PEIS_OUT` as updated by earlier iterations. The dedup
+// variant tracks entries seen so far in `_PEIS_SEEN` since the classic
+// `for %%I in (...)` idiom has no built-in set type.
+fn generate_remove_or_dedup_cmd(name: &str, sep: &str, candidate_value: &str, remove_entry: Option<&str>) -> String {
+    let keep_test = match remove_entry {
+        Some(entry) => format!("if /I not \"%%~I\"==\"{}\" ", entry),
+        None => String::new(),
+    };
+    match remove_entry {
+        Some(_) => format!(
+            "setlocal enabledelayedexpansion\r\nset \"_PEIS_TMP={0}\"\r\nset \"_PEIS_OUT=\"\r\nfor %%I in (\"%_PEIS_TMP:{1}=\" \"%\") do {2}set \"_PEIS_OUT=!_PEIS_OUT!%%~I{1}\"\r\nif \"!_PEIS_OUT:~-1!\"==\"{1}\" set \"_PEIS_OUT=!_PEIS_OUT:~0,-1!\"\r\nendlocal & set \"{3}=%_PEIS_OUT%\"\r\n",
+            candidate_value, sep, keep_test, name
+        ),
+        None => format!(
+            "setlocal enabledelayedexpansion\r\nset \"_PEIS_TMP={0}\"\r\nset \"_PEIS_OUT=\"\r\nset \"_PEIS_SEEN=\"\r\nfor %%I in (\"%_PEIS_TMP:{1}=\" \"%\") do (echo \"!_PEIS_SEEN!\"|findstr /c:\"<%%~I>\" >nul || (set \"_PEIS_SEEN=!_PEIS_SEEN!<%%~I>\" & set \"_PEIS_OUT=!_PEIS_OUT!%%~I{1}\"))\r\nif \"!_PEIS_OUT:~-1!\"==\"{1}\" set \"_PEIS_OUT=!_PEIS_OUT:~0,-1!\"\r\nendlocal & set \"{2}=%_PEIS_OUT%\"\r\n",
+            candidate_value, sep, name
+        ),
+    }
+}
+
+#[test]
+fn test_wrap_dedup_bash() {
+    let wrapped = wrap_dedup(EnvType::BASH, ":", "/a:/b");
+    assert_eq!(wrapped, "$(printf '%s' \"/a:/b\" | awk -v RS=':' -v ORS=':' '!seen[$0]++' | sed 's/:$//')");
+}
+
+#[test]
+fn test_wrap_dedup_powershell() {
+    let wrapped = wrap_dedup(EnvType::POWERSHELL, ";", "$env:PATH");
+    assert_eq!(wrapped, "((\"$env:PATH\") -split ';' | Select-Object -Unique) -join ';'");
+}
+
+#[test]
+fn test_generate_remove_path_value_bash() {
+    let removed = generate_remove_path_value("PATH", "/a", EnvType::BASH, ":");
+    assert_eq!(removed, "$(printf '%s' \"${PATH}\" | awk -v RS=':' -v ORS=':' '$0!=\"/a\"' | sed 's/:$//')");
+}
+
+#[test]
+fn test_generate_remove_path_value_fish() {
+    let removed = generate_remove_path_value("PATH", "/a", EnvType::FISH, " ");
+    assert_eq!(removed, "(string match -v -- '/a' $PATH)");
+}
+
+#[test]
+fn test_generate_remove_or_dedup_cmd_remove_filters_exact_entry() {
+    let block = generate_remove_or_dedup_cmd("PATH", ";", "PATH", Some("C:\\a"));
+    // the filter only drops the exact entry, it doesn't special-case entries
+    // that merely end in the separator
+    assert!(block.contains("if /I not \"%%~I\"==\"C:\\a\" set \"_PEIS_OUT=!_PEIS_OUT!%%~I;\""));
+}
+
+#[test]
+fn test_generate_remove_or_dedup_cmd_dedup_uses_delayed_expansion() {
+    let block = generate_remove_or_dedup_cmd("PATH", ";", "%PATH%;%PATH%", None);
+    assert!(block.starts_with("setlocal enabledelayedexpansion\r\n"));
+    assert!(block.contains("endlocal & set \"PATH=%_PEIS_OUT%\""));
+}
+
+fn generate_mod_env_value(name: &str, value: &str, m: ModType, e: EnvType, separator: Option<&str>, translate_path: bool, path_style: PathStyle, dedup: bool) -> Result<String, String> {
+    let eval_value = transform_vars(value, e)?;
+    // fish lists are real lists, so prepend/append is just "new_value $NAME" / "$NAME new_value",
+    // not a separator-joined string like the other shells
+    if let EnvType::FISH = e {
+        return Ok(match m {
+            ModType::PREPEND_PATH => {
+                let s = format!("{} {}", generate_fix_path(&eval_value, e, translate_path, path_style), generate_get_env(name, e));
+                if dedup { wrap_dedup(e, " ", &s) } else { s }
+            },
+            ModType::APPEND_PATH => {
+                let s = format!("{} {}", generate_get_env(name, e), generate_fix_path(&eval_value, e, translate_path, path_style));
+                if dedup { wrap_dedup(e, " ", &s) } else { s }
+            },
+            ModType::SET => generate_mod_env_set_value(&eval_value, e),
+            ModType::PATH => generate_fix_path(&eval_value, e, translate_path, path_style),
+            ModType::REMOVE_PATH => generate_remove_path_value(name, &generate_fix_path(&eval_value, e, translate_path, path_style), e, " "),
+        })
+    }
+    let sep = separator.unwrap_or_else(|| generate_separator(e));
+    Ok(match m {
         ModType::PREPEND_PATH => {
-            let mut s = generate_fix_path(&eval_value,e);
-            s.push_str(generate_separator(e));
+            let mut s = generate_fix_path(&eval_value,e,translate_path,path_style);
+            s.push_str(sep);
             s.push_str(&generate_get_env(name, e));
-            s
+            if dedup { wrap_dedup(e, sep, &s) } else { s }
         },
         ModType::APPEND_PATH => {
             let mut s = generate_get_env(name, e);
-            s.push_str(generate_separator(e));
-            s.push_str(&generate_fix_path(&eval_value,e));
-            s
+            s.push_str(sep);
+            s.push_str(&generate_fix_path(&eval_value,e,translate_path,path_style));
+            if dedup { wrap_dedup(e, sep, &s) } else { s }
         },
         ModType::SET => generate_mod_env_set_value(&eval_value,e),
-        ModType::PATH => generate_fix_path(&eval_value,e),
-    }
+        ModType::PATH => generate_fix_path(&eval_value,e,translate_path,path_style),
+        ModType::REMOVE_PATH => generate_remove_path_value(name, &generate_fix_path(&eval_value, e, translate_path, path_style), e, sep),
+    })
 }
 
-fn generate_mod_env(name: &str, value: &str, m: ModType, e: EnvType) -> String {
-    let mod_env_val = generate_mod_env_value(name, value, m, e);
-    match e {
+#[test]
+fn test_generate_mod_env_value_custom_separator() {
+    // CLASSPATH-style variables use ':' on bash like PATH, but e.g. ';' is
+    // also a valid override for a list variable that isn't a real path
+    let value = generate_mod_env_value("CLASSPATH", "a.jar", ModType::APPEND_PATH, EnvType::BASH, Some(";"), false, PathStyle::NONE, false).unwrap();
+    assert_eq!(value, "${CLASSPATH};a.jar");
+}
+
+#[test]
+fn test_generate_mod_env_value_default_separator_when_none_given() {
+    let value = generate_mod_env_value("CLASSPATH", "a.jar", ModType::APPEND_PATH, EnvType::BASH, None, false, PathStyle::NONE, false).unwrap();
+    assert_eq!(value, "${CLASSPATH}:a.jar");
+}
+
+#[test]
+fn test_generate_mod_env_value_translate_path_false_suppresses_cygpath() {
+    let value = generate_mod_env_value("CLASSPATH", "a.jar", ModType::APPEND_PATH, EnvType::BASH, None, false, PathStyle::CYGPATH, false).unwrap();
+    assert!(!value.contains("cygpath"), "translate_path=false must suppress cygpath wrapping: {}", value);
+    assert_eq!(value, "${CLASSPATH}:a.jar");
+}
+
+#[test]
+fn test_generate_mod_env_value_translate_path_true_applies_cygpath() {
+    let value = generate_mod_env_value("PATH", "C:\\a", ModType::PREPEND_PATH, EnvType::BASH, None, true, PathStyle::CYGPATH, false).unwrap();
+    assert!(value.contains("cygpath"), "translate_path=true should apply cygpath wrapping: {}", value);
+}
+
+fn generate_mod_env(name: &str, value: &str, m: ModType, e: EnvType, separator: Option<&str>, translate_path: bool, path_style: PathStyle, dedup: bool) -> Result<String, String> {
+    // cmd can't express REMOVE_PATH or a dedup'd prepend/append as a single
+    // `set NAME=value` line, so those cases are built as their own block.
+    if let EnvType::CMD = e {
+        let sep = separator.unwrap_or_else(|| generate_separator(e));
+        match m {
+            ModType::REMOVE_PATH => {
+                let entry = transform_vars(value, e)?;
+                return Ok(generate_remove_or_dedup_cmd(name, sep, &generate_get_env(name, e), Some(&entry)));
+            },
+            ModType::PREPEND_PATH if dedup => {
+                let candidate = generate_mod_env_value(name, value, m, e, separator, translate_path, path_style, false)?;
+                return Ok(generate_remove_or_dedup_cmd(name, sep, &candidate, None));
+            },
+            ModType::APPEND_PATH if dedup => {
+                let candidate = generate_mod_env_value(name, value, m, e, separator, translate_path, path_style, false)?;
+                return Ok(generate_remove_or_dedup_cmd(name, sep, &candidate, None));
+            },
+            _ => {}
+        }
+    }
+    let mod_env_val = generate_mod_env_value(name, value, m, e, separator, translate_path, path_style, dedup)?;
+    // REMOVE_PATH and deduped PREPEND_PATH/APPEND_PATH build a pipeline
+    // expression (parens, `-split`, `|`) rather than a plain string. PowerShell
+    // double-quoted strings only interpolate simple $var references, not
+    // arbitrary expressions, so those cases must be emitted unquoted to
+    // actually be evaluated instead of kept as literal text.
+    let is_powershell_expression = match m {
+        ModType::REMOVE_PATH => true,
+        ModType::PREPEND_PATH | ModType::APPEND_PATH => dedup,
+        ModType::SET | ModType::PATH => false,
+    };
+    Ok(match e {
         EnvType::CMD => format!("set {}={}\r\n", name, &mod_env_val),
+        EnvType::POWERSHELL if is_powershell_expression => format!("$env:{}={}\r\n", name, &mod_env_val),
         EnvType::POWERSHELL => format!("$env:{}=\"{}\"\r\n", name, &mod_env_val),
         EnvType::BASH => format!("export {}={}\n", name, &mod_env_val),
-    }
+        EnvType::FISH => format!("set -gx {} {}\n", name, &mod_env_val),
+    })
+}
+
+#[test]
+fn test_generate_mod_env_remove_path_cmd_reads_the_real_variable() {
+    // regression: the cmd branch must read %PATH%, not the literal name "PATH"
+    let line = generate_mod_env("PATH", "C:\\a", ModType::REMOVE_PATH, EnvType::CMD, None, false, PathStyle::NONE, false).unwrap();
+    assert!(line.contains("set \"_PEIS_TMP=%PATH%\""), "expected to read %PATH%, got: {}", line);
+    assert!(!line.contains("set \"_PEIS_TMP=PATH\""), "must not set _PEIS_TMP to the literal name: {}", line);
+}
+
+#[test]
+fn test_generate_mod_env_remove_path_powershell_is_not_double_quoted() {
+    // regression: wrapping the pipeline expression in "..." leaves it as
+    // literal text instead of letting PowerShell evaluate it
+    let line = generate_mod_env("PATH", "C:\\a", ModType::REMOVE_PATH, EnvType::POWERSHELL, None, false, PathStyle::NONE, false).unwrap();
+    assert!(line.starts_with("$env:PATH=(("), "expected an unquoted expression, got: {}", line);
+    assert!(!line.contains("=\"(("), "pipeline expression must not be double-quoted: {}", line);
+}
+
+#[test]
+fn test_generate_mod_env_dedup_prepend_powershell_is_not_double_quoted() {
+    let line = generate_mod_env("PATH", "C:\\new", ModType::PREPEND_PATH, EnvType::POWERSHELL, None, false, PathStyle::NONE, true).unwrap();
+    assert!(line.starts_with("$env:PATH=(("), "expected an unquoted expression, got: {}", line);
+}
+
+#[test]
+fn test_generate_mod_env_set_powershell_is_still_double_quoted() {
+    let line = generate_mod_env("FOO", "bar", ModType::SET, EnvType::POWERSHELL, None, false, PathStyle::NONE, false).unwrap();
+    assert_eq!(line, "$env:FOO=\"bar\"\r\n");
+}
+
+#[test]
+fn test_generate_mod_env_remove_path_bash() {
+    let line = generate_mod_env("PATH", "C:\\a", ModType::REMOVE_PATH, EnvType::BASH, None, false, PathStyle::NONE, false).unwrap();
+    assert_eq!(line, "export PATH=$(printf '%s' \"${PATH}\" | awk -v RS=':' -v ORS=':' '$0!=\"C:\\a\"' | sed 's/:$//')\n");
+}
+
+#[test]
+fn test_generate_mod_env_remove_path_fish() {
+    let line = generate_mod_env("PATH", "/a", ModType::REMOVE_PATH, EnvType::FISH, None, false, PathStyle::NONE, false).unwrap();
+    assert_eq!(line, "set -gx PATH (string match -v -- '/a' $PATH)\n");
+}
+
+#[test]
+fn test_generate_mod_env_dedup_prepend_cmd_uses_delayed_expansion_block() {
+    let line = generate_mod_env("PATH", "C:\\new", ModType::PREPEND_PATH, EnvType::CMD, None, false, PathStyle::NONE, true).unwrap();
+    assert!(line.starts_with("setlocal enabledelayedexpansion\r\n"));
+    assert!(line.contains("endlocal & set \"PATH=%_PEIS_OUT%\""));
+}
+
+#[test]
+fn test_generate_mod_env_dedup_append_bash() {
+    let line = generate_mod_env("PATH", "/new", ModType::APPEND_PATH, EnvType::BASH, None, false, PathStyle::NONE, true).unwrap();
+    assert_eq!(line, "export PATH=$(printf '%s' \"${PATH}:/new\" | awk -v RS=':' -v ORS=':' '!seen[$0]++' | sed 's/:$//')\n");
 }
 
 fn generate_src_env(file_to_src: &Path, e: EnvType) -> String {
@@ -170,95 +477,371 @@ fn generate_src_env(file_to_src: &Path, e: EnvType) -> String {
         EnvType::CMD => format!("call %~dp0\\{}\r\n", file_to_src.display()),
         EnvType::BASH => format!("source {}\n", file_to_src.display()),
         EnvType::POWERSHELL => format!(". {}\r\n", file_to_src.display()),
+        EnvType::FISH => format!("source {}\n", file_to_src.display()),
+    }
+}
+
+fn line_eol(e: EnvType) -> &'static str {
+    match e {
+        EnvType::CMD | EnvType::POWERSHELL => "\r\n",
+        EnvType::BASH | EnvType::FISH => "\n",
+    }
+}
+
+// cmd and powershell only ever run on Windows, so an `if_os` targeting them
+// can be resolved at generation time by dropping the line entirely; bash and
+// fish run anywhere, so their platform has to be checked at runtime instead.
+fn is_windows_only_shell(e: EnvType) -> bool {
+    match e {
+        EnvType::CMD | EnvType::POWERSHELL => true,
+        EnvType::BASH | EnvType::FISH => false,
+    }
+}
+
+fn validate_if_os(os: &str) -> Result<(), String> {
+    match os {
+        "windows" | "linux" | "macos" => Ok(()),
+        _ => Err(format!("invalid if_os:{}", os))
+    }
+}
+
+fn generate_if_os_runtime_check(os: &str, e: EnvType) -> String {
+    match e {
+        EnvType::BASH => match os {
+            "windows" => "[ -n \"$WINDIR\" ]".to_string(),
+            "macos" => "[ \"$(uname)\" = \"Darwin\" ]".to_string(),
+            "linux" => "[ \"$(uname)\" = \"Linux\" ]".to_string(),
+            _ => unreachable!("if_os is validated before this is called")
+        },
+        EnvType::FISH => match os {
+            "windows" => "test -n \"$WINDIR\"".to_string(),
+            "macos" => "test (uname) = Darwin".to_string(),
+            "linux" => "test (uname) = Linux".to_string(),
+            _ => unreachable!("if_os is validated before this is called")
+        },
+        EnvType::CMD | EnvType::POWERSHELL => unreachable!("if_os is resolved at generation time for cmd/powershell")
+    }
+}
+
+// Wraps an already-generated line (including its trailing line ending) in the
+// `if_exists`/`if_os` conditionals of a command, if any are set. Returns None
+// when the line should be dropped entirely (an `if_os` targeting cmd/powershell
+// that doesn't match the shell's intended platform). Assumes `if_os`, if
+// present, has already passed `validate_if_os`. `label_id` only matters for
+// cmd bodies with more than one statement (see below) and must be unique
+// per call within a script.
+fn apply_conditions(line: &str, e: EnvType, if_exists: Option<&str>, if_os: Option<&str>, label_id: usize) -> Option<String> {
+    if if_exists.is_none() && if_os.is_none() {
+        return Some(line.to_string());
+    }
+    if let Some(os) = if_os {
+        if is_windows_only_shell(e) && os != "windows" {
+            return None;
+        }
+    }
+
+    let mut body = line.trim_end_matches("\r\n").trim_end_matches('\n').to_string();
+    if let Some(path) = if_exists {
+        // cmd expands any %VAR% used inside a parenthesized `( ... )` block
+        // once, when the block is parsed, rather than per statement as each
+        // line executes. That's fine for a single `set` line, but the
+        // multi-statement REMOVE_PATH/dedup blocks rely on reading a %VAR%
+        // they just set earlier in the same block, which breaks if nested in
+        // parens. Guard those with a goto instead so the body still runs as
+        // top-level statements.
+        if let EnvType::CMD = e {
+            if body.contains("\r\n") {
+                return Some(format!(
+                    "if not exist \"{}\" goto :peis_skip_{}\r\n{}\r\n:peis_skip_{}\r\n",
+                    path, label_id, body, label_id
+                ));
+            }
+        }
+        body = match e {
+            EnvType::CMD => format!("if exist \"{}\" ({})", path, body),
+            EnvType::POWERSHELL => format!("if (Test-Path \"{}\") {{ {} }}", path, body),
+            EnvType::BASH => format!("[ -e \"{}\" ] && {}", path, body),
+            EnvType::FISH => format!("test -e \"{}\"; and {}", path, body),
+        };
+    }
+    if let Some(os) = if_os {
+        if !is_windows_only_shell(e) {
+            let check = generate_if_os_runtime_check(os, e);
+            body = match e {
+                EnvType::BASH => format!("{} && {}", check, body),
+                EnvType::FISH => format!("{}; and {}", check, body),
+                EnvType::CMD | EnvType::POWERSHELL => body,
+            };
+        }
     }
+    Some(format!("{}{}", body, line_eol(e)))
+}
+
+#[test]
+fn test_apply_conditions_single_statement() {
+    let wrapped = apply_conditions("set FOO=bar\r\n", EnvType::CMD, Some("C:\\tools"), None, 0).unwrap();
+    assert_eq!(wrapped, "if exist \"C:\\tools\" (set FOO=bar)\r\n");
+}
+
+#[test]
+fn test_apply_conditions_bash() {
+    let wrapped = apply_conditions("export FOO=bar\n", EnvType::BASH, Some("/tools"), None, 0).unwrap();
+    assert_eq!(wrapped, "[ -e \"/tools\" ] && export FOO=bar\n");
+}
+
+#[test]
+fn test_apply_conditions_multi_statement_cmd_uses_goto_not_parens() {
+    // A multi-statement cmd body (as REMOVE_PATH/dedup emit) must not be
+    // nested inside `if exist "..." ( ... )`: cmd expands any %VAR% used
+    // inside a parenthesized block once, at parse time, which breaks a body
+    // that reads a %VAR% it just set on an earlier line of the same block.
+    let body = "set \"_PEIS_OUT=\"\r\nset \"FOO=%_PEIS_OUT%\"\r\n";
+    let wrapped = apply_conditions(body, EnvType::CMD, Some("C:\\tools"), None, 3).unwrap();
+    assert!(!wrapped.contains('('), "multi-statement cmd body must not be paren-wrapped: {}", wrapped);
+    assert!(wrapped.starts_with("if not exist \"C:\\tools\" goto :peis_skip_3\r\n"));
+    assert!(wrapped.contains(":peis_skip_3\r\n"));
 }
 
 #[derive(Deserialize)]
 struct Config  {
     scripts: HashMap<String, Vec<std::collections::HashMap<String, String>>>,
+    #[serde(default)]
+    import: Vec<String>,
+    path_style: Option<String>,
+}
+
+// Loads `path`, recursively pulling in its `import`ed files. Each imported
+// file's scripts are merged under a namespace derived from the import's file
+// stem (e.g. `toolchain.toml` contributes `toolchain.rust`), and any `source`
+// command inside an imported file that points at one of its own sibling
+// scripts is rewritten to the namespaced name so it still resolves after the
+// merge. `visited` tracks the ancestry of the current import chain (not
+// every file loaded during the run) to reject cycles while still allowing
+// diamond-shaped imports, where two files import a shared common file.
+fn load_config(path: &Path, visited: &mut Vec<PathBuf>) -> Config {
+    let canonical = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+    if visited.contains(&canonical) {
+        panic!("import cycle detected while loading {}", path.display());
+    }
+    visited.push(canonical.clone());
+
+    let mut file = match File::open(path) {
+        Err(why) => panic!("couldn't open {}: {}", path.display(), Error::description(&why)),
+        Ok(file) => file,
+    };
+
+    let mut config_string = String::new();
+    if let Err(why) = file.read_to_string(&mut config_string) {
+        panic!("couldn't read {}: {}", path.display(), Error::description(&why))
+    }
+
+    let mut data: Config = toml::from_str(&config_string).unwrap();
+    let base_dir = path.parent().unwrap_or_else(|| Path::new("."));
+
+    let mut merged_scripts = HashMap::new();
+    for import_path_str in &data.import {
+        let import_path = base_dir.join(import_path_str);
+        let namespace = Path::new(import_path_str).file_stem().unwrap().to_string_lossy().into_owned();
+        let imported = load_config(&import_path, visited);
+        let sibling_names: std::collections::HashSet<String> = imported.scripts.keys().cloned().collect();
+
+        for (name, cmds) in imported.scripts {
+            let namespaced_cmds = cmds.into_iter().map(|cmd| {
+                let is_local_source = cmd.get("command").map(|c| &c[..] == "source").unwrap_or(false)
+                    && cmd.get("env").map(|env| sibling_names.contains(env)).unwrap_or(false);
+                if is_local_source {
+                    let mut cmd = cmd;
+                    let env = cmd.get("env").unwrap().clone();
+                    cmd.insert("env".to_string(), format!("{}.{}", namespace, env));
+                    cmd
+                } else {
+                    cmd
+                }
+            }).collect();
+            merged_scripts.insert(format!("{}.{}", namespace, name), namespaced_cmds);
+        }
+    }
+    merged_scripts.extend(data.scripts);
+    data.scripts = merged_scripts;
+    visited.pop();
+    data
+}
+
+#[cfg(test)]
+fn make_test_config_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("portable_env_test_{}_{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[test]
+fn test_load_config_diamond_import_is_not_a_cycle() {
+    // a.toml imports both b.toml and c.toml, which both import the shared
+    // d.toml. That's not a cycle -- d.toml is reached twice along sibling
+    // branches of the import graph, not along one branch importing itself.
+    let dir = make_test_config_dir("diamond");
+    std::fs::write(dir.join("d.toml"), "[scripts]\n").unwrap();
+    std::fs::write(dir.join("b.toml"), "import = [\"d.toml\"]\n[scripts]\n").unwrap();
+    std::fs::write(dir.join("c.toml"), "import = [\"d.toml\"]\n[scripts]\n").unwrap();
+    std::fs::write(dir.join("a.toml"), "import = [\"b.toml\", \"c.toml\"]\n[scripts]\n").unwrap();
+
+    let mut visited = Vec::new();
+    let data = load_config(&dir.join("a.toml"), &mut visited);
+    assert!(visited.is_empty());
+    assert!(data.scripts.is_empty());
+}
+
+#[test]
+#[should_panic(expected = "import cycle detected")]
+fn test_load_config_real_cycle_panics() {
+    let dir = make_test_config_dir("cycle");
+    std::fs::write(dir.join("a.toml"), "import = [\"b.toml\"]\n[scripts]\n").unwrap();
+    std::fs::write(dir.join("b.toml"), "import = [\"a.toml\"]\n[scripts]\n").unwrap();
+
+    let mut visited = Vec::new();
+    load_config(&dir.join("a.toml"), &mut visited);
 }
 
 #[derive(Deserialize)]
 struct Args {
     flag_config: String,
     flag_output: String,
+    flag_check: bool,
 }
 
 fn get_script_output_path(e: EnvType, out_path_str: &str, script_name: &str) -> PathBuf {
     let (subdir, extension) = match e {
         EnvType::CMD => ("cmd", "bat"),
         EnvType::POWERSHELL => ("ps", "ps1"),
-        EnvType::BASH => ("bash", "sh")
+        EnvType::BASH => ("bash", "sh"),
+        EnvType::FISH => ("fish", "fish")
     };
-    let mut fname : String= "env_".to_string();
-    fname.push_str(script_name);
-    Path::new(out_path_str).join(subdir).join(&fname).with_extension(extension)
+    // script_name may be namespaced (e.g. "toolchain.rust"), so the extension is
+    // appended directly rather than via Path::with_extension, which would treat
+    // the namespace separator as the filename's extension dot.
+    let fname = format!("env_{}.{}", script_name, extension);
+    Path::new(out_path_str).join(subdir).join(fname)
 }
 
-fn get_mod_type_by_str(s: &str) -> ModType {
+fn get_mod_type_by_str(s: &str) -> Result<ModType, String> {
     match s {
-        "PREPEND_PATH" => ModType::PREPEND_PATH,
-        "APPEND_PATH" => ModType::APPEND_PATH,
-        "SET" => ModType::SET,
-        "PATH" => ModType::PATH,
-        _ => panic!("invalid mod type:{}", s)
+        "PREPEND_PATH" => Ok(ModType::PREPEND_PATH),
+        "APPEND_PATH" => Ok(ModType::APPEND_PATH),
+        "SET" => Ok(ModType::SET),
+        "PATH" => Ok(ModType::PATH),
+        "REMOVE_PATH" => Ok(ModType::REMOVE_PATH),
+        _ => Err(format!("invalid mod type:{}", s))
+    }
+}
+
+// A problem found while validating or generating one command of one script.
+// Carries enough context (script name, command index, message naming the
+// offending key/value) to be printed as a standalone diagnostic line.
+struct ValidationError {
+    script: String,
+    command_index: usize,
+    message: String,
+}
+
+impl std::fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "script '{}', command #{}: {}", self.script, self.command_index, self.message)
+    }
+}
+
+fn build_command_line(command: &std::collections::HashMap<String, String>, out_path_str: &str, e: EnvType, default_path_style: PathStyle, command_index: usize) -> Result<Option<String>, String> {
+    let command_type = command.get("command").ok_or("missing key: command")?;
+    match &command_type[..] {
+        "env" => {
+            let key = command.get("key").ok_or("missing key: key")?;
+            let value = command.get("value").ok_or("missing key: value")?;
+            let mode = get_mod_type_by_str(command.get("mode").ok_or("missing key: mode")?)?;
+            let separator = command.get("separator").map(|s| &s[..]);
+            let translate_path = command.get("translate_path")
+                .map(|s| s == "true")
+                .unwrap_or_else(|| default_translate_path(mode, e));
+            let path_style = match command.get("path_style") {
+                Some(s) => get_path_style_by_str(s)?,
+                None => default_path_style,
+            };
+            let if_exists = command.get("if_exists").map(|s| &s[..]);
+            let if_os = match command.get("if_os") {
+                Some(s) => { validate_if_os(s)?; Some(&s[..]) },
+                None => None,
+            };
+            let dedup = command.get("dedup").map(|s| s == "true").unwrap_or(false);
+            let line = generate_mod_env(key, value, mode, e, separator, translate_path, path_style, dedup)?;
+            Ok(apply_conditions(&line, e, if_exists, if_os, command_index))
+        },
+        "source" => {
+            let env = command.get("env").ok_or("missing key: env")?;
+            let env_name = get_script_output_path(e, out_path_str, env);
+            let file_to_source = Path::new(env_name.file_name().unwrap());
+            let if_exists = command.get("if_exists").map(|s| &s[..]);
+            let if_os = match command.get("if_os") {
+                Some(s) => { validate_if_os(s)?; Some(&s[..]) },
+                None => None,
+            };
+            let line = generate_src_env(file_to_source, e);
+            Ok(apply_conditions(&line, e, if_exists, if_os, command_index))
+        },
+        c => Err(format!("invalid command type: {}", c))
     }
 }
 
-fn generate_script(script_name_pair: &(String, Vec<std::collections::HashMap<String, String>>), out_path_str: &str, e: EnvType) {
-    let script_name = &script_name_pair.0;
-    let cmds = &script_name_pair.1;
-    let out_path = get_script_output_path(e, out_path_str, &script_name);
+// Renders the full contents of one script for one shell, collecting every
+// per-command problem instead of stopping at the first one.
+fn build_script(script_name: &str, cmds: &[std::collections::HashMap<String, String>], out_path_str: &str, e: EnvType, default_path_style: PathStyle) -> Result<String, Vec<ValidationError>> {
+    let mut out_content = String::new();
+    match e {
+        EnvType::CMD => out_content.push_str(&format!("@rem {}\r\n", AUTOREMOVE_MARKER)),
+        EnvType::BASH => out_content.push_str(&format!("# {}\n", AUTOREMOVE_MARKER)),
+        EnvType::POWERSHELL => out_content.push_str(&format!("# {}\r\n", AUTOREMOVE_MARKER)),
+        EnvType::FISH => out_content.push_str(&format!("# {}\n", AUTOREMOVE_MARKER)),
+    }
+
+    let mut errors = Vec::new();
+    for (command_index, command) in cmds.iter().enumerate() {
+        match build_command_line(command, out_path_str, e, default_path_style, command_index) {
+            Ok(Some(line)) => out_content.push_str(&line),
+            Ok(None) => {},
+            Err(message) => errors.push(ValidationError {
+                script: script_name.to_string(),
+                command_index,
+                message,
+            }),
+        }
+    }
+
+    if errors.is_empty() { Ok(out_content) } else { Err(errors) }
+}
+
+fn generate_script(script_name: &str, cmds: &[std::collections::HashMap<String, String>], out_path_str: &str, e: EnvType, default_path_style: PathStyle) -> Result<(), Vec<ValidationError>> {
+    let content = build_script(script_name, cmds, out_path_str, e, default_path_style)?;
+    let out_path = get_script_output_path(e, out_path_str, script_name);
+
     if let Err(why) = std::fs::create_dir_all(out_path.parent().unwrap())  {
          panic!("couldn't create dir {}: {}", out_path.parent().unwrap().display(),
                                                    Error::description(&why))
     }
-    
+
     let mut file = match File::create(&out_path) {
         Err(why) => panic!("couldn't create {}: {}",
                            out_path.display(),
                            Error::description(&why)),
         Ok(file) => file,
     };
-    
-    let mut out_content = String::new();
-    match e {
-        EnvType::CMD => {
-            out_content.push_str(&format!("@rem {}\r\n", AUTOREMOVE_MARKER));
-        },
-        EnvType::BASH => {
-            out_content.push_str(&format!("# {}\n", AUTOREMOVE_MARKER));
-        }
-        EnvType::POWERSHELL => {
-            out_content.push_str(&format!("# {}\r\n", AUTOREMOVE_MARKER));
-        }
-    }
-    for command in cmds {
-        match &command.get("command").unwrap()[..] { 
-            "env" => {
-                let key = command.get("key").unwrap();
-                let value = command.get("value").unwrap();
-                let mode : ModType = get_mod_type_by_str(&command.get("mode").unwrap());
-                out_content.push_str(&generate_mod_env(key, value, mode,e))
-            },
-            "source" => {
-                let env = command.get("env").unwrap();
-                let env_name = get_script_output_path(e, out_path_str, env);
-                let file_to_source = Path::new(env_name.file_name().unwrap());
-                out_content.push_str(&generate_src_env(file_to_source, e));
-            }
-            c @ _ => panic!("invalid command type: {}", c)
-        }
-    }
 
-    if let Err(why) = file.write_all(&out_content[..].as_bytes())  {
+    if let Err(why) = file.write_all(content.as_bytes())  {
          panic!("couldn't write {}: {}", out_path.display(),
                                                    Error::description(&why))
     }
+    Ok(())
 }
 
 fn remove_old_scripts(dir: &str) {
-    for &subdir in ["cmd", "ps", "bash"].iter() {
+    for &subdir in ["cmd", "ps", "bash", "fish"].iter() {
         let p = &[&dir, subdir].iter().collect::<PathBuf>();
         if !Path::exists(p) {
             continue;
@@ -290,24 +873,54 @@ fn main() {
 
     let config_path = Path::new(&args.flag_config);
 
-    let mut file = match File::open(&config_path) {
-        Err(why) => panic!("couldn't open {}: {}", config_path.display(),
-                                                   Error::description(&why)),
-        Ok(file) => file,
+    let mut visited = Vec::new();
+    let data: Config = load_config(&config_path, &mut visited);
+    let default_path_style = match data.path_style.as_ref().map(|s| get_path_style_by_str(s)) {
+        None => PathStyle::CYGPATH,
+        Some(Ok(style)) => style,
+        Some(Err(message)) => {
+            eprintln!("invalid config: {}", message);
+            std::process::exit(1);
+        }
     };
-    
-    let mut config_string = String::new();
-    if let Err(why) = file.read_to_string(&mut config_string)  {
-         panic!("couldn't read {}: {}", config_path.display(),
-                                                   Error::description(&why))
+
+    let envs = [EnvType::CMD, EnvType::BASH, EnvType::POWERSHELL, EnvType::FISH];
+
+    if args.flag_check {
+        let mut errors = Vec::new();
+        let mut would_write = Vec::new();
+        for (script_name, cmds) in &data.scripts {
+            for env in &envs {
+                match build_script(script_name, cmds, &args.flag_output[..], *env, default_path_style) {
+                    Ok(_) => would_write.push(get_script_output_path(*env, &args.flag_output[..], script_name)),
+                    Err(errs) => errors.extend(errs),
+                }
+            }
+        }
+        if errors.is_empty() {
+            println!("config OK, would write:");
+            for path in would_write {
+                println!("  {}", path.display());
+            }
+        } else {
+            for err in &errors {
+                println!("{}", err);
+            }
+            std::process::exit(1);
+        }
+        return;
     }
 
     remove_old_scripts(&args.flag_output[..]);
 
-    let data: Config = toml::from_str(&config_string).unwrap();
-    for script in data.scripts {
-        for env in &[EnvType::CMD, EnvType::BASH, EnvType::POWERSHELL] {
-            generate_script(&script, &args.flag_output[..], *env);
+    for (script_name, cmds) in &data.scripts {
+        for env in &envs {
+            if let Err(errors) = generate_script(script_name, cmds, &args.flag_output[..], *env, default_path_style) {
+                for err in &errors {
+                    eprintln!("{}", err);
+                }
+                std::process::exit(1);
+            }
         }
     }
 }